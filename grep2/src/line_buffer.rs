@@ -1,6 +1,10 @@
 use std::cmp;
-use std::io;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem::MaybeUninit;
+use std::ops;
 use std::ptr;
+use std::sync::Mutex;
 
 use memchr::{memchr, memrchr};
 
@@ -82,6 +86,9 @@ struct Config {
     buffer_alloc: BufferAllocation,
     /// When set, the presence of the given byte indicates binary content.
     binary: BinaryDetection,
+    /// Whether to prefer a ring-buffer backed allocation over the default
+    /// heap-allocated buffer, when the current platform supports it.
+    ring_buffer: bool,
 }
 
 impl Default for Config {
@@ -91,6 +98,7 @@ impl Default for Config {
             lineterm: b'\n',
             buffer_alloc: BufferAllocation::default(),
             binary: BinaryDetection::default(),
+            ring_buffer: false,
         }
     }
 }
@@ -111,10 +119,7 @@ impl LineBufferBuilder {
     pub fn build(&self) -> LineBuffer {
         LineBuffer {
             config: self.config,
-            buf: vec![0; self.config.capacity],
-            pos: 0,
-            last_lineterm: 0,
-            end: 0,
+            buf: Buffer::new(self.config.capacity, self.config.ring_buffer),
             absolute_byte_offset: 0,
             binary_byte_offset: None,
         }
@@ -186,6 +191,32 @@ impl LineBufferBuilder {
         self.config.binary = detection;
         self
     }
+
+    /// Whether to back this buffer with a ring buffer allocation instead of
+    /// the default heap-allocated `Vec<u8>`.
+    ///
+    /// On platforms that support it, a ring buffer maps its backing
+    /// allocation twice back-to-back in memory, so that the logically
+    /// contiguous window of unconsumed bytes is always physically contiguous
+    /// too, even once it has wrapped around the end of the allocation. This
+    /// lets `fill` make room for new data by simply advancing past consumed
+    /// bytes, rather than by copying the remaining bytes to the front of the
+    /// buffer whenever the tail runs out of free space, which can be a
+    /// meaningful win for workloads with
+    /// many short lines and a small buffer capacity.
+    ///
+    /// Since the double mapping fixes the buffer's capacity up front, a ring
+    /// buffer can't be grown in place. If the buffer allocation strategy
+    /// requires growing beyond the initial capacity (see `buffer_alloc`),
+    /// the line buffer transparently falls back to a heap-allocated buffer.
+    /// The same fallback occurs when the current platform doesn't support
+    /// the double-mapping trick.
+    ///
+    /// This is disabled by default.
+    pub fn ring_buffer(&mut self, yes: bool) -> &mut LineBufferBuilder {
+        self.config.ring_buffer = yes;
+        self
+    }
 }
 
 /// A line buffer reader efficiently reads a line oriented buffer from an
@@ -272,35 +303,901 @@ impl<'b, R: io::Read> LineBufferReader<'b, R> {
     pub fn consume_all(&mut self) {
         self.line_buffer.consume_all();
     }
+
+    /// Run `f` over the current complete-line contents of the buffer and
+    /// consume however many bytes `f` reports having processed.
+    ///
+    /// This is equivalent to `consume(f(buffer()))`, but lets a searcher
+    /// read and advance the buffer's cursor through a single borrow instead
+    /// of a separate `buffer()` call followed by `consume()`.
+    pub fn consume_with<F: FnOnce(&[u8]) -> usize>(&mut self, f: F) {
+        let amt = f(self.line_buffer.buffer());
+        self.line_buffer.consume(amt);
+    }
 }
 
-/// A line buffer manages a (typically fixed) buffer for holding lines.
+/// A layer in a composable stack of buffered readers.
 ///
-/// Callers should create line buffers sparingly and reuse them when possible.
-/// Line buffers cannot be used directly, but instead must be used via the
-/// LineBufferReader.
+/// This mirrors the `fill`/`buffer`/`consume` contract already implemented
+/// by `LineBufferReader`, so a searcher can be written generically against
+/// this trait without caring whether the bytes it sees are raw input, a
+/// length-limited prefix of it, or the decoded output of a streaming
+/// decompressor. Layers compose by wrapping one another, with each layer
+/// responsible for ensuring its own `buffer()` is contiguous and that any
+/// data it can't yet fully process (e.g. an undrained decoder output, or a
+/// dangling partial line) is carried forward to the next `fill` rather than
+/// being split.
+pub trait BufferedLayer {
+    /// Refill this layer's buffer. Returns `false` once this layer has
+    /// nothing left to yield and its buffer has been fully consumed.
+    fn fill(&mut self) -> Result<bool, io::Error>;
+
+    /// The currently buffered, not-yet-consumed bytes.
+    fn buffer(&self) -> &[u8];
+
+    /// Consume `amt` bytes from the front of `buffer`. This must be less
+    /// than or equal to `buffer().len()`.
+    fn consume(&mut self, amt: usize);
+
+    /// Consume the entirety of `buffer`.
+    ///
+    /// This is a convenience function for `consume(buffer().len())`.
+    fn consume_all(&mut self) {
+        let amt = self.buffer().len();
+        self.consume(amt);
+    }
+}
+
+impl<'b, R: io::Read> BufferedLayer for LineBufferReader<'b, R> {
+    fn fill(&mut self) -> Result<bool, io::Error> {
+        LineBufferReader::fill(self)
+    }
+
+    fn buffer(&self) -> &[u8] {
+        LineBufferReader::buffer(self)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        LineBufferReader::consume(self, amt)
+    }
+}
+
+/// A layer that caps how many bytes an inner layer will yield in total,
+/// regardless of how much data remains underneath it.
+///
+/// This is useful for bounding how much of a source is searched, e.g. when
+/// only a prefix of a large file should be considered.
+#[derive(Debug)]
+pub struct Limitor<L> {
+    inner: L,
+    remaining: u64,
+}
+
+impl<L: BufferedLayer> Limitor<L> {
+    /// Wrap `inner` so that at most `limit` bytes are ever yielded from it.
+    pub fn new(inner: L, limit: u64) -> Limitor<L> {
+        Limitor { inner, remaining: limit }
+    }
+}
+
+impl<L: BufferedLayer> BufferedLayer for Limitor<L> {
+    fn fill(&mut self) -> Result<bool, io::Error> {
+        if self.remaining == 0 {
+            return Ok(false);
+        }
+        self.inner.fill()
+    }
+
+    fn buffer(&self) -> &[u8] {
+        let buf = self.inner.buffer();
+        let max = cmp::min(buf.len() as u64, self.remaining) as usize;
+        &buf[..max]
+    }
+
+    fn consume(&mut self, amt: usize) {
+        assert!(amt as u64 <= self.remaining);
+        self.remaining -= amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// A layer that exposes the decoded output of a streaming decoder (for
+/// example, a gzip or zstd decompressor) through the standard buffered
+/// layer interface, so a searcher can grep compressed input transparently.
+///
+/// `D` is any streaming decoder that implements `io::Read` over the
+/// underlying compressed source; this layer doesn't know or care which
+/// compression format `D` decodes. It's built directly on top of
+/// `LineBuffer`, so it gets the same partial-data carrying behavior at
+/// refill boundaries for free: bytes `D` hasn't finished decoding simply
+/// aren't returned by `D::read` yet, and whatever this layer has already
+/// decoded but not yet consumed stays put across `fill` calls exactly like
+/// a `LineBufferReader`'s trailing partial line does.
+#[derive(Debug)]
+pub struct Decompressor<D> {
+    decoder: D,
+    buf: LineBuffer,
+}
+
+impl<D: io::Read> Decompressor<D> {
+    /// Wrap `decoder`'s decoded output in a buffered layer with the given
+    /// capacity.
+    pub fn new(decoder: D, capacity: usize) -> Decompressor<D> {
+        let buf = LineBufferBuilder::new().capacity(capacity).build();
+        Decompressor { decoder, buf }
+    }
+}
+
+impl<D: io::Read> BufferedLayer for Decompressor<D> {
+    fn fill(&mut self) -> Result<bool, io::Error> {
+        self.buf.fill(&mut self.decoder)
+    }
+
+    fn buffer(&self) -> &[u8] {
+        self.buf.buffer()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.buf.consume(amt)
+    }
+}
+
+/// A line buffer reader that reads a line oriented buffer from an arbitrary
+/// seekable reader, from the end toward the beginning.
+///
+/// This is useful for implementing "show the last N matching lines"
+/// functionality, or for searching a large file from its most recently
+/// written data backward, without having to load the entire contents into
+/// memory.
+///
+/// Unlike `LineBufferReader`, whose `buffer` grows from the beginning of the
+/// underlying reader's contents toward the end, `ReverseLineBufferReader`'s
+/// `buffer` grows from the end toward the beginning, and `consume` advances
+/// toward the front of the buffered region rather than away from it. EOF for
+/// this reader therefore means that the beginning of the underlying reader's
+/// contents has been reached.
+pub struct ReverseLineBufferReader<R> {
+    rdr: R,
+    capacity: usize,
+    lineterm: u8,
+    binary: BinaryDetection,
+    /// The backing storage. Valid data is always right-aligned: it occupies
+    /// `[data_start, buf.len())`. New (earlier) data is prepended into the
+    /// free space in front of `data_start`. When there is none, the existing
+    /// contents are moved further to the right, either over consumed bytes
+    /// at the tail or into a larger allocation, since a plain `Vec` can't
+    /// cheaply grow at the front.
+    ///
+    /// Bytes in `[0, data_start)` are never read from (every byte that
+    /// `data_start` moves back over is immediately overwritten by the read
+    /// that moved it), so they don't need to be initialized up front.
+    buf: Box<[MaybeUninit<u8>]>,
+    /// The index of the first byte of data currently buffered. The bytes in
+    /// `[data_start, complete_start)` are a partial line whose beginning has
+    /// not yet been read.
+    data_start: usize,
+    /// The index of the start of the region of complete, unconsumed lines.
+    complete_start: usize,
+    /// The index one past the last unconsumed byte of complete line data.
+    /// Consuming shrinks this value toward `complete_start`.
+    valid_end: usize,
+    /// The absolute offset, relative to the beginning of `rdr`'s contents,
+    /// of `data_start`. The next `fill` reads the block ending here.
+    cursor: u64,
+    binary_byte_offset: Option<u64>,
+}
+
+impl<R> fmt::Debug for ReverseLineBufferReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReverseLineBufferReader")
+            .field("capacity", &self.capacity)
+            .field("lineterm", &self.lineterm)
+            .field("binary", &self.binary)
+            .field("buf_len", &self.buf.len())
+            .field("data_start", &self.data_start)
+            .field("complete_start", &self.complete_start)
+            .field("valid_end", &self.valid_end)
+            .field("cursor", &self.cursor)
+            .field("binary_byte_offset", &self.binary_byte_offset)
+            .finish()
+    }
+}
+
+impl<R: Read + Seek> ReverseLineBufferReader<R> {
+    /// Create a new reverse line buffer reader that reads from the end of
+    /// `rdr` back toward the beginning, using the given configuration.
+    pub fn new(
+        mut rdr: R,
+        capacity: usize,
+        lineterm: u8,
+    ) -> Result<ReverseLineBufferReader<R>, io::Error> {
+        let len = rdr.seek(SeekFrom::End(0))?;
+        let capacity = cmp::max(1, capacity);
+        // SAFETY: `MaybeUninit<u8>` needs no initialization, and nothing
+        // reads from `buf` until `data_start` has been advanced past it by
+        // a `read_exact` into the corresponding range.
+        let buf = unsafe {
+            let mut v = Vec::with_capacity(capacity);
+            v.set_len(capacity);
+            v.into_boxed_slice()
+        };
+        Ok(ReverseLineBufferReader {
+            rdr,
+            capacity,
+            lineterm,
+            binary: BinaryDetection::None,
+            buf,
+            data_start: capacity,
+            complete_start: capacity,
+            valid_end: capacity,
+            cursor: len,
+            binary_byte_offset: None,
+        })
+    }
+
+    /// Like `new`, but sets the binary detection behavior of this reader.
+    pub fn with_binary_detection(
+        rdr: R,
+        capacity: usize,
+        lineterm: u8,
+        binary: BinaryDetection,
+    ) -> Result<ReverseLineBufferReader<R>, io::Error> {
+        let mut me = ReverseLineBufferReader::new(rdr, capacity, lineterm)?;
+        me.binary = binary;
+        Ok(me)
+    }
+
+    /// The absolute byte offset which corresponds to the starting offset of
+    /// the data returned by `buffer`, relative to the beginning of the
+    /// underlying reader's contents.
+    pub fn absolute_byte_offset(&self) -> u64 {
+        self.cursor + (self.complete_start - self.data_start) as u64
+    }
+
+    /// If binary data was detected, then this returns the absolute byte
+    /// offset at which binary data was initially found.
+    pub fn binary_byte_offset(&self) -> Option<u64> {
+        self.binary_byte_offset
+    }
+
+    /// Fill the contents of this buffer by reading the next (i.e., earlier)
+    /// block of data from the underlying reader, prepending it to whatever
+    /// partial line is currently buffered.
+    ///
+    /// If the beginning of the underlying reader's contents has been
+    /// reached, then `false` is returned. Otherwise, `true` is returned.
+    ///
+    /// This forwards any errors returned by the underlying reader.
+    pub fn fill(&mut self) -> Result<bool, io::Error> {
+        if self.binary.is_quit() && self.binary_byte_offset.is_some() {
+            return Ok(!self.buffer().is_empty());
+        }
+        if self.cursor == 0 && self.data_start == self.complete_start {
+            return Ok(false);
+        }
+        loop {
+            if self.cursor == 0 {
+                // Nothing left to read, so whatever partial line remains is,
+                // by definition, complete: there is nothing before it.
+                self.complete_start = self.data_start;
+                return Ok(true);
+            }
+
+            self.ensure_capacity();
+            let readlen =
+                cmp::min(self.capacity as u64, self.cursor) as usize;
+            let new_data_start = self.data_start - readlen;
+            self.rdr.seek(SeekFrom::Start(self.cursor - readlen as u64))?;
+            // SAFETY: `u8` has no invalid bit patterns, so it's sound to
+            // hand `read_exact` a `&mut [u8]` view of this uninitialized
+            // range; it will be fully overwritten (or the call will fail
+            // and we bail out via `?` without reading it).
+            let dest = unsafe {
+                slice_from_uninit_mut(
+                    &mut self.buf[new_data_start..self.data_start],
+                )
+            };
+            self.rdr.read_exact(dest)?;
+
+            let old_cursor = self.cursor;
+            self.cursor -= readlen as u64;
+            self.data_start = new_data_start;
+            // SAFETY: This is exactly the range `read_exact` just filled
+            // above, so it's fully initialized.
+            let newbytes = unsafe {
+                slice_from_uninit_mut(
+                    &mut self.buf[new_data_start..new_data_start + readlen],
+                )
+            };
+
+            match self.binary {
+                BinaryDetection::None => {} // nothing to do
+                BinaryDetection::Quit(byte) => {
+                    if let Some(i) = memrchr(byte, newbytes) {
+                        // Everything at or before the last binary byte in
+                        // this block is unreachable: we stop reading any
+                        // further back.
+                        self.binary_byte_offset =
+                            Some(old_cursor - readlen as u64 + i as u64);
+                        self.data_start = new_data_start + i + 1;
+                        self.complete_start = self.data_start;
+                        self.cursor += (i + 1) as u64;
+                        return Ok(true);
+                    }
+                }
+                BinaryDetection::Convert(byte) => {
+                    if let Some(i) = replace_bytes(
+                        newbytes,
+                        byte,
+                        self.lineterm,
+                    ) {
+                        self.binary_byte_offset =
+                            Some(old_cursor - readlen as u64 + i as u64);
+                    }
+                }
+            }
+
+            if self.cursor == 0 {
+                // We just read all the way back to the beginning of the
+                // reader's contents, so everything we've buffered so far
+                // is, by definition, complete.
+                self.complete_start = self.data_start;
+                return Ok(true);
+            }
+
+            if let Some(i) = memchr(self.lineterm, newbytes) {
+                self.complete_start = new_data_start + i + 1;
+                return Ok(true);
+            }
+            // No line terminator in this block yet, so we don't have a
+            // complete line. Read further back to find one.
+        }
+    }
+
+    /// Return the currently buffered, complete lines, in their original byte
+    /// order. Callers interested in reverse line order should scan this
+    /// buffer from the end (e.g., with `memrchr`) and `consume` each line as
+    /// it's found.
+    pub fn buffer(&self) -> &[u8] {
+        // SAFETY: `[complete_start, valid_end)` always falls within
+        // `[data_start, buf.len())`, which is only ever extended by a
+        // `read_exact` that fully initializes it.
+        unsafe {
+            slice_from_uninit(&self.buf[self.complete_start..self.valid_end])
+        }
+    }
+
+    /// Consume the number of bytes provided, starting from the end of
+    /// `buffer`. This must be less than or equal to the number of bytes
+    /// returned by `buffer`.
+    pub fn consume(&mut self, amt: usize) {
+        assert!(amt <= self.buffer().len());
+        self.valid_end -= amt;
+    }
+
+    /// Consumes the remainder of the buffer. Subsequent calls to `buffer`
+    /// are guaranteed to return an empty slice until the buffer is refilled.
+    pub fn consume_all(&mut self) {
+        let amt = self.buffer().len();
+        self.consume(amt);
+    }
+
+    /// Ensure there's room to prepend at least one more byte. Consumed bytes
+    /// at the tail are reclaimed first, by right-shifting the existing
+    /// contents over them; the backing allocation is grown (and the existing
+    /// contents right-shifted into it) only if that frees less than
+    /// `capacity` bytes.
+    fn ensure_capacity(&mut self) {
+        if self.data_start > 0 {
+            return;
+        }
+        // Reclaim in whole multiples of `capacity`, so that `data_start`
+        // stays a multiple of `capacity` and a full-sized read always fits.
+        let len = self.buf.len();
+        let shift = (len - self.valid_end) / self.capacity * self.capacity;
+        if shift > 0 {
+            self.buf.copy_within(0..self.valid_end, shift);
+            self.data_start += shift;
+            self.complete_start += shift;
+            self.valid_end += shift;
+            return;
+        }
+        let newlen = self.buf.len() * 2;
+        let delta = newlen - self.buf.len();
+        // SAFETY: `data_start == 0` means every byte of the current `buf` is
+        // initialized, so it's sound to copy it wholesale into the tail of
+        // the new, larger allocation; the new leading `[0, delta)` region is
+        // left uninitialized until a subsequent `read_exact` fills it.
+        let mut newbuf = unsafe {
+            let mut v = Vec::with_capacity(newlen);
+            v.set_len(newlen);
+            v.into_boxed_slice()
+        };
+        newbuf[delta..].copy_from_slice(&self.buf);
+        self.buf = newbuf;
+        self.data_start += delta;
+        self.complete_start += delta;
+        self.valid_end += delta;
+    }
+}
+
+/// The backing storage for a buffer: either a plain heap allocation, or, on
+/// platforms that support it and when requested, a ring buffer whose
+/// backing allocation is mapped twice back-to-back so that a logical window
+/// never straddles a physical wrap point.
+#[derive(Debug)]
+enum Storage {
+    Heap(HeapBuf),
+    Ring(ring::RingBuffer),
+}
+
+impl Clone for Storage {
+    fn clone(&self) -> Storage {
+        match *self {
+            Storage::Heap(ref heap) => Storage::Heap(heap.clone()),
+            // Recreating the double mapping can fail under the same
+            // transient OS resource limits (e.g. a map count limit) that
+            // can make the original allocation fail, just at an inopportune
+            // moment instead of up front. A transient limit shouldn't abort
+            // the whole search, so fall back to an equivalent heap copy
+            // instead of panicking.
+            Storage::Ring(ref ring) => match ring.try_clone() {
+                Some(ring) => Storage::Ring(ring),
+                None => {
+                    // Copy the whole doubled mapping, since a ring buffer's
+                    // logical offsets can reach up to `2 * capacity`.
+                    let len = ring.as_slice().len();
+                    let mut heap = HeapBuf::new(len);
+                    heap.as_mut_slice().copy_from_slice(ring.as_slice());
+                    heap.filled = len;
+                    Storage::Heap(heap)
+                }
+            },
+        }
+    }
+}
+
+impl Storage {
+    /// Build the initial backing storage for a buffer of the given capacity.
+    ///
+    /// When `ring_buffer` is requested but isn't supported on this platform,
+    /// this falls back to a heap allocation of the same capacity.
+    fn new(capacity: usize, ring_buffer: bool) -> Storage {
+        if ring_buffer {
+            if let Some(ring) = ring::RingBuffer::new(capacity) {
+                return Storage::Ring(ring);
+            }
+        }
+        Storage::Heap(HeapBuf::new(capacity))
+    }
+
+    /// The total capacity of this buffer's backing storage.
+    fn capacity(&self) -> usize {
+        match *self {
+            Storage::Heap(ref heap) => heap.data.len(),
+            Storage::Ring(ref ring) => ring.capacity(),
+        }
+    }
+
+    /// Whether this buffer is backed by a fixed-size ring buffer allocation,
+    /// and therefore cannot be grown in place.
+    fn is_ring(&self) -> bool {
+        match *self {
+            Storage::Heap(_) => false,
+            Storage::Ring(_) => true,
+        }
+    }
+
+    /// Replace this buffer's storage with a heap allocation of the given
+    /// length, copying over the `keep` bytes starting at the physical index
+    /// `start` of the current contents.
+    ///
+    /// This is how a ring buffer falls back to a heap allocation once it
+    /// needs to grow past its fixed capacity.
+    fn convert_to_heap(&mut self, newlen: usize, start: usize, keep: usize) {
+        let mut heap = HeapBuf::new(newlen);
+        heap.data[..keep].copy_from_slice(unsafe {
+            slice_to_uninit(&self.as_slice()[start..start + keep])
+        });
+        heap.filled = keep;
+        *self = Storage::Heap(heap);
+    }
+
+    /// Grow this buffer's storage to `newlen`, preserving its contents.
+    ///
+    /// This only applies to heap-backed storage, since a ring buffer's
+    /// capacity is fixed by its double mapping.
+    fn grow(&mut self, newlen: usize) {
+        match *self {
+            Storage::Heap(ref mut heap) => heap.grow(newlen),
+            Storage::Ring(_) => unreachable!("ring buffers can't be grown"),
+        }
+    }
+
+    /// Record that the leading `upto` bytes of this buffer's storage have
+    /// now been initialized by a read.
+    ///
+    /// This only has an effect on heap-backed storage. A ring buffer's
+    /// backing pages are always zero-initialized by the operating system,
+    /// so there's nothing to track.
+    fn note_filled(&mut self, upto: usize) {
+        if let Storage::Heap(ref mut heap) = *self {
+            heap.filled = cmp::max(heap.filled, upto);
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match *self {
+            Storage::Heap(ref heap) => heap.as_slice(),
+            Storage::Ring(ref ring) => ring.as_slice(),
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match *self {
+            Storage::Heap(ref mut heap) => heap.as_mut_slice(),
+            Storage::Ring(ref mut ring) => ring.as_mut_slice(),
+        }
+    }
+}
+
+/// A heap-allocated buffer that avoids the cost of zero-initializing bytes
+/// that haven't been read into yet.
+///
+/// `filled` tracks the number of leading bytes of `data` that have actually
+/// been written to by a previous read. `Buffer` guarantees that every byte
+/// it exposes falls within this initialized prefix.
+struct HeapBuf {
+    data: Box<[MaybeUninit<u8>]>,
+    filled: usize,
+}
+
+impl HeapBuf {
+    /// Allocate a new, uninitialized heap buffer with room for `capacity`
+    /// bytes.
+    fn new(capacity: usize) -> HeapBuf {
+        // SAFETY: An array of `MaybeUninit<u8>` needs no initialization.
+        let data = unsafe {
+            let mut v = Vec::with_capacity(capacity);
+            v.set_len(capacity);
+            v.into_boxed_slice()
+        };
+        HeapBuf { data, filled: 0 }
+    }
+
+    /// Grow this buffer's capacity to `newlen`, preserving the initialized
+    /// prefix. The newly added tail is left uninitialized.
+    fn grow(&mut self, newlen: usize) {
+        let mut newbuf = HeapBuf::new(newlen);
+        newbuf.data[..self.filled].copy_from_slice(&self.data[..self.filled]);
+        newbuf.filled = self.filled;
+        *self = newbuf;
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: `u8` has no invalid bit patterns, so it's always sound to
+        // view a `[MaybeUninit<u8>]` as a `[u8]`. Every caller is still
+        // responsible for only reading bytes known to be within `filled`.
+        unsafe { slice_from_uninit(&self.data) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: See `as_slice`.
+        unsafe { slice_from_uninit_mut(&mut self.data) }
+    }
+}
+
+impl Clone for HeapBuf {
+    fn clone(&self) -> HeapBuf {
+        let mut heap = HeapBuf::new(self.data.len());
+        heap.data[..self.filled].copy_from_slice(&self.data[..self.filled]);
+        heap.filled = self.filled;
+        heap
+    }
+}
+
+impl fmt::Debug for HeapBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HeapBuf")
+            .field("capacity", &self.data.len())
+            .field("filled", &self.filled)
+            .finish()
+    }
+}
+
+/// View an initialized byte slice as a `MaybeUninit<u8>` slice, for copying
+/// into not-yet-initialized storage.
+unsafe fn slice_to_uninit(bytes: &[u8]) -> &[MaybeUninit<u8>] {
+    &*(bytes as *const [u8] as *const [MaybeUninit<u8>])
+}
+
+/// View a (possibly partially uninitialized) `MaybeUninit<u8>` slice as a
+/// `u8` slice. Sound because `u8` has no invalid bit patterns; callers must
+/// still avoid reading past the known-initialized prefix.
+unsafe fn slice_from_uninit(bytes: &[MaybeUninit<u8>]) -> &[u8] {
+    &*(bytes as *const [MaybeUninit<u8>] as *const [u8])
+}
+
+unsafe fn slice_from_uninit_mut(bytes: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    &mut *(bytes as *mut [MaybeUninit<u8>] as *mut [u8])
+}
+
+/// Encapsulates the backing storage for a `LineBuffer` together with the
+/// `pos`, `last_lineterm` and `end` indices that carve it up.
+///
+/// `Buffer` is the sole owner of the `pos <= last_lineterm <= end` invariant,
+/// together with `end <= capacity` for heap-backed storage or `end - pos <=
+/// capacity` for a ring buffer (whose offsets are logical, and reach up to
+/// `2 * capacity`): every method either upholds it automatically or
+/// documents the precondition under which it's safe to call. Callers (in
+/// particular `LineBuffer`) therefore never need to re-validate indices
+/// themselves once they've gotten a slice out of `contents` or `free_mut`.
 #[derive(Clone, Debug)]
-pub struct LineBuffer {
-    /// The configuration of this buffer.
-    config: Config,
-    /// The primary buffer with which to hold data.
-    buf: Vec<u8>,
-    /// The current position of this buffer. This is always a valid sliceable
-    /// index into `buf`, and its maximum value is the length of `buf`.
+struct Buffer {
+    storage: Storage,
+    /// The current position of this buffer. This is always a valid logical
+    /// offset into `storage`, and its maximum value is `end`.
     pos: usize,
     /// The end position of searchable content in this buffer. This is either
     /// set to just after the final line terminator in the buffer, or to just
     /// after the end of the last byte emitted by the reader when the reader
     /// has been exhausted.
     last_lineterm: usize,
-    /// The end position of the buffer. This is always greater than or equal to
-    /// lastnl. The bytes between lastnl and end, if any, always correspond to
-    /// a partial line.
+    /// The end position of the buffer. This is always greater than or equal
+    /// to `last_lineterm`. The bytes between `last_lineterm` and `end`, if
+    /// any, always correspond to a partial line.
     end: usize,
-    /// The absolute byte offset corresponding to `pos`. This is most typically
-    /// not a valid index into addressable memory, but rather, an offset that
-    /// is relative to all data that passes through a line buffer (since
-    /// construction or since the last time `clear` was called).
+}
+
+impl Buffer {
+    /// Build a new buffer with the given capacity.
+    fn new(capacity: usize, ring_buffer: bool) -> Buffer {
+        Buffer {
+            storage: Storage::new(capacity, ring_buffer),
+            pos: 0,
+            last_lineterm: 0,
+            end: 0,
+        }
+    }
+
+    /// Reset this buffer's indices, as if freshly constructed. The backing
+    /// storage is left untouched.
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.last_lineterm = 0;
+        self.end = 0;
+    }
+
+    fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Return the physical index into `storage` that corresponds to the
+    /// logical offset `i`.
+    ///
+    /// For a heap-backed buffer, logical and physical offsets always
+    /// coincide. For a ring buffer, the logical offset is taken modulo the
+    /// buffer's capacity, relying on the fact that the backing allocation is
+    /// mapped twice back-to-back.
+    fn physical(&self, i: usize) -> usize {
+        if self.storage.is_ring() {
+            i % self.storage.capacity()
+        } else {
+            i
+        }
+    }
+
+    /// Return the complete-line contents of this buffer, i.e. everything in
+    /// `[pos, last_lineterm)`.
+    fn contents(&self) -> &[u8] {
+        let start = self.physical(self.pos);
+        let len = self.last_lineterm - self.pos;
+        &self.storage.as_slice()[start..start + len]
+    }
+
+    /// Return the free space beyond `end` as a mutable slice, ready to be
+    /// read into.
+    fn free_mut(&mut self) -> &mut [u8] {
+        let start = self.physical(self.end);
+        if self.storage.is_ring() {
+            let freelen = self.storage.capacity() - (self.end - self.pos);
+            &mut self.storage.as_mut_slice()[start..start + freelen]
+        } else {
+            &mut self.storage.as_mut_slice()[start..]
+        }
+    }
+
+    /// Return a mutable view of the `len` bytes most recently made valid by
+    /// advancing `end`, i.e. `[end - len, end)`. Used to run binary
+    /// detection and to search for a line terminator over bytes that were
+    /// just read, without a second bounds check against `end`.
+    fn recently_read_mut(&mut self, len: usize) -> &mut [u8] {
+        let start = self.physical(self.end - len);
+        &mut self.storage.as_mut_slice()[start..start + len]
+    }
+
+    /// Extend `end` by `amt`, noting that the storage has now been
+    /// initialized up to the new `end` (a no-op for ring-backed storage).
+    fn advance_end(&mut self, amt: usize) {
+        self.end += amt;
+        self.storage.note_filled(self.end);
+    }
+
+    fn set_last_lineterm(&mut self, at: usize) {
+        self.last_lineterm = at;
+    }
+
+    /// Truncate `end` (and `last_lineterm` along with it) to `at`, e.g. once
+    /// binary data has been found at that position.
+    fn truncate(&mut self, at: usize) {
+        self.end = at;
+        self.last_lineterm = at;
+    }
+
+    /// Consume the number of bytes provided. This must be less than or equal
+    /// to the number of bytes returned by `contents`.
+    fn consume(&mut self, amt: usize) {
+        assert!(amt <= self.contents().len());
+        self.pos += amt;
+
+        // For a ring buffer, the logical offsets grow without bound as the
+        // window slides forward. Periodically rebase them down by a full
+        // capacity so that they don't overflow on long-running searches.
+        // This never changes the physical byte a logical offset refers to,
+        // since it's equivalent to moving by a whole number of wraps.
+        if self.storage.is_ring() {
+            let cap = self.storage.capacity();
+            while self.pos >= cap {
+                self.pos -= cap;
+                self.last_lineterm -= cap;
+                self.end -= cap;
+            }
+        }
+    }
+
+    /// Roll the unconsumed parts of the buffer to the front.
+    ///
+    /// This operation is idempotent.
+    ///
+    /// After rolling, `last_lineterm` and `end` point to the same location,
+    /// and `pos` is always set to `0`.
+    ///
+    /// When this buffer is backed by a ring buffer, rolling is a no-op: the
+    /// double mapping already guarantees that the unconsumed window is
+    /// physically contiguous no matter where it sits logically, so there's
+    /// nothing to copy.
+    fn roll(&mut self) {
+        if self.storage.is_ring() {
+            return;
+        }
+
+        if self.pos == self.end {
+            self.pos = 0;
+            self.last_lineterm = 0;
+            self.end = 0;
+            return;
+        }
+
+        assert!(self.pos < self.end && self.end <= self.storage.capacity());
+        let roll_len = self.end - self.pos;
+        unsafe {
+            // SAFETY: A buffer contains Copy data, so there's no problem
+            // moving it around. Safety also depends on our indices being
+            // in bounds, which they should always be, and we enforce with
+            // an assert above.
+            //
+            // TODO: It seems like it should be possible to do this in safe
+            // code that results in the same codegen.
+            let buf = self.storage.as_mut_slice();
+            ptr::copy(
+                buf[self.pos..].as_ptr(),
+                buf.as_mut_ptr(),
+                roll_len,
+            );
+        }
+        self.pos = 0;
+        self.last_lineterm = roll_len;
+        self.end = self.last_lineterm;
+    }
+
+    /// Try to free up room at the tail of the buffer without allocating, by
+    /// rolling the unconsumed region down to the front.
+    ///
+    /// Returns `true` if reclaiming actually created more free space (and
+    /// thus performed the roll), or `false` if there was nothing to gain
+    /// (either because no bytes have been consumed yet, or because this
+    /// buffer is ring-backed and has no "move it down" step). Callers
+    /// should re-check `free_mut` after a `true` result, since reclaiming
+    /// isn't guaranteed to free *enough* space, only *some*.
+    ///
+    /// This mirrors the reclaim-before-grow strategy used by tokio's
+    /// `BytesMut`: a buffer sized for the common case can keep pace with
+    /// occasional long lines without repeatedly doubling in capacity.
+    fn try_reclaim(&mut self) -> bool {
+        if self.storage.is_ring() || self.pos == 0 {
+            return false;
+        }
+        self.roll();
+        true
+    }
+
+    /// Ensures that the internal buffer has a non-zero amount of free space
+    /// in which to read more data. If there is no free space, then more is
+    /// allocated. If the allocation must exceed the configured limit, then
+    /// this returns an error.
+    fn grow(
+        &mut self,
+        min_capacity: usize,
+        alloc: BufferAllocation,
+    ) -> Result<(), io::Error> {
+        if !self.free_mut().is_empty() {
+            return Ok(());
+        }
+        if self.try_reclaim() && !self.free_mut().is_empty() {
+            return Ok(());
+        }
+        let additional = match alloc {
+            BufferAllocation::Eager => self.storage.capacity() * 2,
+            BufferAllocation::Error(limit) => {
+                let used = self.storage.capacity() - min_capacity;
+                // A ring buffer's page rounding can alone exceed `limit`.
+                let n = cmp::min(
+                    self.storage.capacity() * 2,
+                    limit.saturating_sub(used),
+                );
+                if n == 0 {
+                    let msg = format!(
+                        "configured allocation limit ({}) exceeded", limit);
+                    return Err(io::Error::new(io::ErrorKind::Other, msg));
+                }
+                n
+            }
+        };
+        assert!(additional > 0);
+        let newlen = self.storage.capacity() + additional;
+        if self.storage.is_ring() {
+            // A ring buffer's capacity is fixed by its double mapping, so
+            // growing means falling back to a plain heap allocation. `pos`
+            // and `end` become physical indices again once we do, so we
+            // rebase everything relative to `pos` first.
+            let unconsumed = self.end - self.pos;
+            let start = self.physical(self.pos);
+            self.storage.convert_to_heap(newlen, start, unconsumed);
+            self.last_lineterm -= self.pos;
+            self.end -= self.pos;
+            self.pos = 0;
+        } else {
+            self.storage.grow(newlen);
+        }
+        assert!(!self.free_mut().is_empty());
+        Ok(())
+    }
+}
+
+/// A line buffer manages a (typically fixed) buffer for holding lines.
+///
+/// Callers should create line buffers sparingly and reuse them when possible.
+/// Line buffers cannot be used directly, but instead must be used via the
+/// LineBufferReader.
+#[derive(Clone, Debug)]
+pub struct LineBuffer {
+    /// The configuration of this buffer.
+    config: Config,
+    /// The indices and backing storage for this buffer.
+    buf: Buffer,
+    /// The absolute byte offset corresponding to `buf`'s `pos`. This is most
+    /// typically not a valid index into addressable memory, but rather, an
+    /// offset that is relative to all data that passes through a line
+    /// buffer (since construction or since the last time `clear` was
+    /// called).
     ///
     /// When the line buffer reaches EOF, this is set to the position just
     /// after the last byte read from the underlying reader.
@@ -313,9 +1210,7 @@ pub struct LineBuffer {
 impl LineBuffer {
     /// Reset this buffer, such that it can be used with a new reader.
     fn clear(&mut self) {
-        self.pos = 0;
-        self.last_lineterm = 0;
-        self.end = 0;
+        self.buf.reset();
         self.absolute_byte_offset = 0;
         self.binary_byte_offset = None;
     }
@@ -350,20 +1245,13 @@ impl LineBuffer {
 
     /// Return the contents of this buffer.
     fn buffer(&self) -> &[u8] {
-        &self.buf[self.pos..self.last_lineterm]
-    }
-
-    /// Return the contents of the free space beyond the end of the buffer as
-    /// a mutable slice.
-    fn free_buffer(&mut self) -> &mut [u8] {
-        &mut self.buf[self.end..]
+        self.buf.contents()
     }
 
     /// Consume the number of bytes provided. This must be less than or equal
     /// to the number of bytes returned by `buffer`.
     fn consume(&mut self, amt: usize) {
-        assert!(amt <= self.buffer().len());
-        self.pos += amt;
+        self.buf.consume(amt);
         self.absolute_byte_offset += amt as u64;
     }
 
@@ -376,10 +1264,9 @@ impl LineBuffer {
         self.consume(amt);
     }
 
-    /// Fill the contents of this buffer by discarding the part of the buffer
-    /// that has been consumed. The free space created by discarding the
-    /// consumed part of the buffer is then filled with new data from the given
-    /// reader.
+    /// Fill the contents of this buffer with new data from the given reader,
+    /// reclaiming the already-consumed part of the buffer first if (and only
+    /// if) there isn't enough free space at the tail to read into.
     ///
     /// Callers should provide the same reader to this line buffer in
     /// subsequent calls to fill. A different reader can only be used
@@ -401,15 +1288,14 @@ impl LineBuffer {
             return Ok(!self.buffer().is_empty());
         }
 
-        self.roll();
-        assert_eq!(self.pos, 0);
         loop {
-            self.ensure_capacity()?;
-            let readlen = rdr.read(self.free_buffer())?;
+            self.buf.grow(self.config.capacity, self.config.buffer_alloc)?;
+            let readlen = rdr.read(self.buf.free_mut())?;
             if readlen == 0 {
                 // We're only done reading for good once the caller has
                 // consumed everything.
-                self.last_lineterm = self.end;
+                let end = self.buf.end();
+                self.buf.set_last_lineterm(end);
                 return Ok(!self.buffer().is_empty());
             }
 
@@ -417,24 +1303,25 @@ impl LineBuffer {
             // the bytes that we do binary detection on, and also the bytes we
             // search to find the last line terminator. We need a mutable slice
             // in the case of binary conversion.
-            let oldend = self.end;
-            self.end += readlen;
-            let newbytes = &mut self.buf[oldend..self.end];
+            let oldend = self.buf.end();
+            let pos = self.buf.pos();
+            self.buf.advance_end(readlen);
+            let newbytes = self.buf.recently_read_mut(readlen);
 
             // Binary detection.
             match self.config.binary {
                 BinaryDetection::None => {} // nothing to do
                 BinaryDetection::Quit(byte) => {
                     if let Some(i) = memchr(byte, newbytes) {
-                        self.end = oldend + i;
-                        self.last_lineterm = self.end;
+                        self.buf.truncate(oldend + i);
                         self.binary_byte_offset =
-                            Some(self.absolute_byte_offset + self.end as u64);
+                            Some(self.absolute_byte_offset
+                                 + (oldend + i - pos) as u64);
                         return Ok(true);
                     }
                 }
                 BinaryDetection::Convert(byte) => {
-                    if let Some(mut i) = replace_bytes(
+                    if let Some(i) = replace_bytes(
                         newbytes,
                         byte,
                         self.config.lineterm,
@@ -443,7 +1330,7 @@ impl LineBuffer {
                         if self.binary_byte_offset.is_none() {
                             self.binary_byte_offset =
                                 Some(self.absolute_byte_offset
-                                     + (oldend + i) as u64);
+                                     + (oldend + i - pos) as u64);
                         }
                     }
                 }
@@ -451,75 +1338,548 @@ impl LineBuffer {
 
             // Update our `last_lineterm` positions if we read one.
             if let Some(i) = memrchr(self.config.lineterm, newbytes) {
-                self.last_lineterm = oldend + i + 1;
+                self.buf.set_last_lineterm(oldend + i + 1);
                 return Ok(true);
             }
             // At this point, if we couldn't find a line terminator, then we
             // don't have a complete line. Therefore, we try to read more!
         }
     }
+}
 
-    /// Roll the unconsumed parts of the buffer to the front.
+/// A thread-safe pool of reusable `LineBuffer` allocations.
+///
+/// In a parallel search over many files, each worker would otherwise
+/// allocate (and then immediately free) a full buffer's worth of capacity
+/// per file. A pool lets a worker check out a buffer that a previous search
+/// already allocated instead, so the allocator only has to work as hard as
+/// the number of buffers actually in flight, not the number of files
+/// searched.
+///
+/// Buffers are recycled on a simple free-list, guarded by a mutex. A buffer
+/// that's grown (via `BufferAllocation::Eager` or `Error`) far beyond the
+/// pool's configured capacity is dropped instead of recycled when it's
+/// returned, so one file with a pathologically long line doesn't cause
+/// every later checkout to retain an oversized allocation.
+#[derive(Debug)]
+pub struct LineBufferPool {
+    config: Config,
+    stack: Mutex<Vec<LineBuffer>>,
+}
+
+impl LineBufferPool {
+    /// A checked-in buffer is only recycled if its capacity is no more than
+    /// this many times the pool's configured capacity. Anything bigger is
+    /// freed instead, to bound how much memory the pool can retain.
+    const MAX_RETAINED_CAPACITY_MULTIPLE: usize = 8;
+
+    /// Create a new pool. Buffers checked out from this pool are built
+    /// according to the given builder's configuration.
+    pub fn new(builder: &LineBufferBuilder) -> LineBufferPool {
+        LineBufferPool { config: builder.config, stack: Mutex::new(vec![]) }
+    }
+
+    /// Check out a buffer from this pool, allocating a new one if none are
+    /// currently available for reuse.
     ///
-    /// This operation is idempotent.
+    /// The returned guard derefs to the underlying `LineBuffer` (suitable
+    /// for passing to `LineBufferReader::new`) and returns the buffer to
+    /// the pool for reuse once it's dropped.
+    pub fn checkout(&self) -> LineBufferPoolGuard<'_> {
+        let buf = self.stack.lock().unwrap().pop().unwrap_or_else(|| {
+            LineBuffer {
+                config: self.config,
+                buf: Buffer::new(
+                    self.config.capacity,
+                    self.config.ring_buffer,
+                ),
+                absolute_byte_offset: 0,
+                binary_byte_offset: None,
+            }
+        });
+        LineBufferPoolGuard { pool: self, buf: Some(buf) }
+    }
+}
+
+/// A `LineBuffer` checked out of a `LineBufferPool`.
+///
+/// Dereferences to the underlying `LineBuffer`. When dropped, the buffer is
+/// cleared and returned to the pool, unless it's grown too large to be
+/// worth retaining, in which case it's simply freed.
+#[derive(Debug)]
+pub struct LineBufferPoolGuard<'p> {
+    pool: &'p LineBufferPool,
+    buf: Option<LineBuffer>,
+}
+
+impl<'p> ops::Deref for LineBufferPoolGuard<'p> {
+    type Target = LineBuffer;
+
+    fn deref(&self) -> &LineBuffer {
+        self.buf.as_ref().expect("buffer taken before drop")
+    }
+}
+
+impl<'p> ops::DerefMut for LineBufferPoolGuard<'p> {
+    fn deref_mut(&mut self) -> &mut LineBuffer {
+        self.buf.as_mut().expect("buffer taken before drop")
+    }
+}
+
+impl<'p> Drop for LineBufferPoolGuard<'p> {
+    fn drop(&mut self) {
+        let mut buf = self.buf.take().expect("buffer taken before drop");
+        let max = self.pool.config.capacity
+            * LineBufferPool::MAX_RETAINED_CAPACITY_MULTIPLE;
+        if buf.buf.capacity() <= max {
+            buf.clear();
+            self.pool.stack.lock().unwrap().push(buf);
+        }
+    }
+}
+
+/// A ring buffer implementation that doubles the mapping of its backing
+/// allocation, so that logical windows near the end of the allocation are
+/// always physically contiguous.
+mod ring {
+    /// A fixed-size buffer whose backing allocation is mapped twice
+    /// back-to-back in virtual memory.
     ///
-    /// After rolling, `last_lineterm` and `end` point to the same location,
-    /// and `pos` is always set to `0`.
-    fn roll(&mut self) {
-        if self.pos == self.end {
-            self.pos = 0;
-            self.last_lineterm = 0;
-            self.end = 0;
-            return;
+    /// That is, for a buffer of capacity `cap`, bytes `[0, cap)` and
+    /// `[cap, 2*cap)` of the mapping always refer to the same physical
+    /// memory. This means any logical slice `[start, start + len)` with
+    /// `len <= cap`, taken modulo `cap`, is always a valid, physically
+    /// contiguous slice of the mapping.
+    #[derive(Debug)]
+    pub struct RingBuffer {
+        map: Mapping,
+        cap: usize,
+    }
+
+    impl RingBuffer {
+        /// Attempt to create a new ring buffer with room for at least
+        /// `capacity` bytes, rounded up to the platform's page size.
+        ///
+        /// Returns `None` if the current platform doesn't support the
+        /// double-mapping trick, or if the mapping otherwise fails (for
+        /// example, because the process has hit a map count limit).
+        /// Callers should fall back to a plain heap allocation in that case.
+        pub fn new(capacity: usize) -> Option<RingBuffer> {
+            let cap = round_up_to_page_size(cmp::max(1, capacity));
+            Mapping::double(cap).map(|map| RingBuffer { map, cap })
         }
 
-        assert!(self.pos < self.end && self.end <= self.buf.len());
-        let roll_len = self.end - self.pos;
-        unsafe {
-            // SAFETY: A buffer contains Copy data, so there's no problem
-            // moving it around. Safety also depends on our indices being
-            // in bounds, which they should always be, and we enforce with
-            // an assert above.
-            //
-            // TODO: It seems like it should be possible to do this in safe
-            // code that results in the same codegen.
-            ptr::copy(
-                self.buf[self.pos..].as_ptr(),
-                self.buf.as_mut_ptr(),
-                roll_len,
-            );
+        /// Attempt to clone this ring buffer by recreating the double
+        /// mapping at the same capacity and copying this buffer's contents
+        /// into it.
+        ///
+        /// Returns `None` if the mapping can't be recreated, for example
+        /// because the process has since hit a map count limit -- even
+        /// though constructing the original mapping succeeded. Callers
+        /// should fall back to a plain heap copy in that case rather than
+        /// treating it as fatal.
+        pub fn try_clone(&self) -> Option<RingBuffer> {
+            let mut ring = RingBuffer::new(self.cap)?;
+            ring.as_mut_slice()[..self.cap]
+                .copy_from_slice(&self.as_slice()[..self.cap]);
+            Some(ring)
+        }
+
+        /// The total capacity of this ring buffer.
+        pub fn capacity(&self) -> usize {
+            self.cap
+        }
+
+        /// Return the full `2 * capacity` doubled mapping, so that a
+        /// logical slice which wraps past `capacity` remains a single
+        /// contiguous, in-bounds range.
+        pub fn as_slice(&self) -> &[u8] {
+            self.map.as_slice()
+        }
+
+        pub fn as_mut_slice(&mut self) -> &mut [u8] {
+            self.map.as_mut_slice()
         }
-        self.pos = 0;
-        self.last_lineterm = roll_len;
-        self.end = self.last_lineterm;
     }
 
-    /// Ensures that the internal buffer has a non-zero amount of free space
-    /// in which to read more data. If there is no free space, then more is
-    /// allocated. If the allocation must exceed the configured limit, then
-    /// this returns an error.
-    fn ensure_capacity(&mut self) -> Result<(), io::Error> {
-        if !self.free_buffer().is_empty() {
-            return Ok(());
+    use std::cmp;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use self::unix::Mapping;
+    #[cfg(windows)]
+    use self::windows::Mapping;
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        windows,
+    )))]
+    use self::fallback::Mapping;
+
+    /// Round `n` up to the nearest multiple of the page (or, on Windows,
+    /// allocation granularity) size.
+    #[cfg(unix)]
+    fn round_up_to_page_size(n: usize) -> usize {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        let page_size = cmp::max(1, page_size);
+        n.div_ceil(page_size) * page_size
+    }
+
+    #[cfg(windows)]
+    fn round_up_to_page_size(n: usize) -> usize {
+        let granularity = self::windows::allocation_granularity();
+        let granularity = cmp::max(1, granularity);
+        n.div_ceil(granularity) * granularity
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn round_up_to_page_size(n: usize) -> usize {
+        n
+    }
+
+    /// A double-mapped allocation of `2 * cap` bytes of address space, backed
+    /// by `cap` bytes of physical memory shared between both halves.
+    ///
+    /// This relies on `memfd_create`, which is Linux/Android-specific (it
+    /// isn't defined by `libc` for macOS/BSD targets at all, so gating this
+    /// any wider than that would be a compile-time failure, not merely an
+    /// unsupported platform). Other Unix platforms use the `fallback`
+    /// module below and transparently get the heap-backed buffer instead.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    mod unix {
+        use std::io;
+        use std::ptr;
+        use std::slice;
+
+        #[derive(Debug)]
+        pub struct Mapping {
+            ptr: *mut u8,
+            cap: usize,
         }
-        let additional = match self.config.buffer_alloc {
-            BufferAllocation::Eager => self.buf.len() * 2,
-            BufferAllocation::Error(limit) => {
-                let used = self.buf.len() - self.config.capacity;
-                let n = cmp::min(self.buf.len() * 2, limit - used);
-                if n == 0 {
-                    let msg = format!(
-                        "configured allocation limit ({}) exceeded", limit);
-                    return Err(io::Error::new(io::ErrorKind::Other, msg));
+
+        // SAFETY: `Mapping` owns its memory mapping outright and doesn't
+        // expose any interior mutability that isn't already synchronized by
+        // `&mut` access to `RingBuffer`.
+        unsafe impl Send for Mapping {}
+        unsafe impl Sync for Mapping {}
+
+        impl Mapping {
+            /// Create a new mapping of `2 * cap` bytes, with both halves
+            /// backed by the same `cap` bytes of physical memory.
+            ///
+            /// `cap` must already be a multiple of the page size.
+            pub fn double(cap: usize) -> Option<Mapping> {
+                unsafe { Self::double_unchecked(cap).ok() }
+            }
+
+            unsafe fn double_unchecked(cap: usize) -> io::Result<Mapping> {
+                // Create an anonymous, memory-backed file descriptor that we
+                // can map twice. `memfd_create` keeps this off the
+                // filesystem entirely.
+                let fd = libc::syscall(libc::SYS_memfd_create, b"ripgrep-ring-buffer\0".as_ptr(), 0);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
                 }
-                n
+                let fd = fd as libc::c_int;
+                if libc::ftruncate(fd, cap as libc::off_t) != 0 {
+                    let err = io::Error::last_os_error();
+                    libc::close(fd);
+                    return Err(err);
+                }
+
+                // Reserve `2 * cap` bytes of address space up front so that
+                // the two halves we map next land next to each other.
+                let base = libc::mmap(
+                    ptr::null_mut(),
+                    cap * 2,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if base == libc::MAP_FAILED {
+                    libc::close(fd);
+                    return Err(io::Error::last_os_error());
+                }
+
+                let first = libc::mmap(
+                    base,
+                    cap,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    fd,
+                    0,
+                );
+                let second = if first == libc::MAP_FAILED {
+                    libc::MAP_FAILED
+                } else {
+                    libc::mmap(
+                        (base as *mut u8).add(cap) as *mut libc::c_void,
+                        cap,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_SHARED | libc::MAP_FIXED,
+                        fd,
+                        0,
+                    )
+                };
+                // The file descriptor isn't needed once both mappings exist.
+                libc::close(fd);
+
+                if first == libc::MAP_FAILED || second == libc::MAP_FAILED {
+                    libc::munmap(base, cap * 2);
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(Mapping { ptr: base as *mut u8, cap })
             }
-        };
-        assert!(additional > 0);
-        let newlen = self.buf.len() + additional;
-        self.buf.resize(newlen, 0);
-        assert!(!self.free_buffer().is_empty());
-        Ok(())
+
+            pub fn as_slice(&self) -> &[u8] {
+                unsafe { slice::from_raw_parts(self.ptr, self.cap * 2) }
+            }
+
+            pub fn as_mut_slice(&mut self) -> &mut [u8] {
+                unsafe { slice::from_raw_parts_mut(self.ptr, self.cap * 2) }
+            }
+        }
+
+        impl Drop for Mapping {
+            fn drop(&mut self) {
+                unsafe {
+                    libc::munmap(self.ptr as *mut libc::c_void, self.cap * 2);
+                }
+            }
+        }
+    }
+
+    /// A double-mapped allocation on Windows, built from a single anonymous
+    /// file mapping object viewed twice, back-to-back, via a pair of
+    /// `MapViewOfFileEx` calls.
+    #[cfg(windows)]
+    mod windows {
+        use std::ffi::c_void;
+        use std::io;
+        use std::ptr;
+        use std::slice;
+
+        #[allow(non_camel_case_types)]
+        type HANDLE = *mut c_void;
+        #[allow(non_camel_case_types)]
+        type LPVOID = *mut c_void;
+        #[allow(non_camel_case_types)]
+        type DWORD = u32;
+        #[allow(non_camel_case_types)]
+        type BOOL = i32;
+        #[allow(non_camel_case_types)]
+        type SIZE_T = usize;
+
+        const PAGE_READWRITE: DWORD = 0x04;
+        const FILE_MAP_ALL_ACCESS: DWORD = 0x000F001F;
+        const MEM_RESERVE: DWORD = 0x00002000;
+        const MEM_RELEASE: DWORD = 0x00008000;
+        const INVALID_HANDLE_VALUE: HANDLE = -1isize as HANDLE;
+
+        // How many times we'll retry the reserve-release-map dance below if
+        // another thread steals our address range out from under us between
+        // the release and the first map.
+        const MAX_ATTEMPTS: u32 = 8;
+
+        #[repr(C)]
+        struct SystemInfo {
+            // The real `SYSTEM_INFO` union is `wProcessorArchitecture` (u16)
+            // plus `wReserved` (u16), or `dwOemId` (u32) aliased over the
+            // same four bytes. We never read it, so a plain `u32` matches
+            // its size without needing the union.
+            _w_processor_architecture_and_reserved: u32,
+            dw_page_size: DWORD,
+            lp_minimum_application_address: LPVOID,
+            lp_maximum_application_address: LPVOID,
+            dw_active_processor_mask: usize,
+            dw_number_of_processors: DWORD,
+            dw_processor_type: DWORD,
+            dw_allocation_granularity: DWORD,
+            w_processor_level: u16,
+            w_processor_revision: u16,
+        }
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn CreateFileMappingW(
+                hFile: HANDLE,
+                lpAttributes: LPVOID,
+                flProtect: DWORD,
+                dwMaximumSizeHigh: DWORD,
+                dwMaximumSizeLow: DWORD,
+                lpName: LPVOID,
+            ) -> HANDLE;
+            fn CloseHandle(hObject: HANDLE) -> BOOL;
+            fn VirtualAlloc(
+                lpAddress: LPVOID,
+                dwSize: SIZE_T,
+                flAllocationType: DWORD,
+                flProtect: DWORD,
+            ) -> LPVOID;
+            fn VirtualFree(
+                lpAddress: LPVOID,
+                dwSize: SIZE_T,
+                dwFreeType: DWORD,
+            ) -> BOOL;
+            fn MapViewOfFileEx(
+                hFileMappingObject: HANDLE,
+                dwDesiredAccess: DWORD,
+                dwFileOffsetHigh: DWORD,
+                dwFileOffsetLow: DWORD,
+                dwNumberOfBytesToMap: SIZE_T,
+                lpBaseAddress: LPVOID,
+            ) -> LPVOID;
+            fn UnmapViewOfFile(lpBaseAddress: LPVOID) -> BOOL;
+            fn GetSystemInfo(lpSystemInfo: *mut SystemInfo);
+        }
+
+        /// The allocation granularity that view addresses must be aligned
+        /// to, per `GetSystemInfo` (typically 64 KiB).
+        pub fn allocation_granularity() -> usize {
+            unsafe {
+                let mut info: SystemInfo = std::mem::zeroed();
+                GetSystemInfo(&mut info);
+                info.dw_allocation_granularity as usize
+            }
+        }
+
+        #[derive(Debug)]
+        pub struct Mapping {
+            ptr: *mut u8,
+            cap: usize,
+        }
+
+        // SAFETY: Same rationale as the Unix `Mapping`: ownership of the
+        // mapping is exclusive and all mutation goes through `&mut`.
+        unsafe impl Send for Mapping {}
+        unsafe impl Sync for Mapping {}
+
+        impl Mapping {
+            /// Create a new mapping of `2 * cap` bytes, with both halves
+            /// backed by the same `cap` bytes of physical memory.
+            ///
+            /// `cap` must already be a multiple of the allocation
+            /// granularity.
+            pub fn double(cap: usize) -> Option<Mapping> {
+                unsafe { Self::double_unchecked(cap).ok() }
+            }
+
+            unsafe fn double_unchecked(cap: usize) -> io::Result<Mapping> {
+                let handle = CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    ptr::null_mut(),
+                    PAGE_READWRITE,
+                    (cap >> 32) as DWORD,
+                    cap as DWORD,
+                    ptr::null_mut(),
+                );
+                if handle.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+
+                // There's no atomic "reserve a double-wide range and map
+                // into both halves" primitive on Windows, so we reserve,
+                // release, and immediately re-map, retrying on the rare
+                // chance another allocation wins the race in between.
+                let result = (|| {
+                    for _ in 0..MAX_ATTEMPTS {
+                        let base = VirtualAlloc(
+                            ptr::null_mut(),
+                            cap * 2,
+                            MEM_RESERVE,
+                            0,
+                        );
+                        if base.is_null() {
+                            return Err(io::Error::last_os_error());
+                        }
+                        if VirtualFree(base, 0, MEM_RELEASE) == 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+
+                        let first = MapViewOfFileEx(
+                            handle,
+                            FILE_MAP_ALL_ACCESS,
+                            0,
+                            0,
+                            cap,
+                            base,
+                        );
+                        let second = if first.is_null() {
+                            ptr::null_mut()
+                        } else {
+                            MapViewOfFileEx(
+                                handle,
+                                FILE_MAP_ALL_ACCESS,
+                                0,
+                                0,
+                                cap,
+                                (base as *mut u8).add(cap) as LPVOID,
+                            )
+                        };
+                        if !first.is_null() && !second.is_null() {
+                            return Ok(base as *mut u8);
+                        }
+                        if !first.is_null() {
+                            UnmapViewOfFile(first);
+                        }
+                        // Lost the race for this address range; try again
+                        // with a fresh reservation.
+                    }
+                    Err(io::Error::last_os_error())
+                })();
+
+                CloseHandle(handle);
+                result.map(|ptr| Mapping { ptr, cap })
+            }
+
+            pub fn as_slice(&self) -> &[u8] {
+                unsafe { slice::from_raw_parts(self.ptr, self.cap * 2) }
+            }
+
+            pub fn as_mut_slice(&mut self) -> &mut [u8] {
+                unsafe { slice::from_raw_parts_mut(self.ptr, self.cap * 2) }
+            }
+        }
+
+        impl Drop for Mapping {
+            fn drop(&mut self) {
+                unsafe {
+                    UnmapViewOfFile(self.ptr as LPVOID);
+                    UnmapViewOfFile(
+                        (self.ptr as *mut u8).add(self.cap) as LPVOID,
+                    );
+                }
+            }
+        }
+    }
+
+    /// A fallback for platforms where we don't (yet) know how to perform the
+    /// double-mapping trick -- including non-Linux/Android Unix platforms
+    /// such as macOS and the BSDs, which lack `memfd_create`. `RingBuffer::
+    /// new` never succeeds with this mapping, so callers always fall back
+    /// to a heap allocation instead.
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        windows,
+    )))]
+    mod fallback {
+        #[derive(Debug)]
+        pub struct Mapping(());
+
+        impl Mapping {
+            pub fn double(_cap: usize) -> Option<Mapping> {
+                None
+            }
+
+            pub fn as_slice(&self) -> &[u8] {
+                &[]
+            }
+
+            pub fn as_mut_slice(&mut self) -> &mut [u8] {
+                &mut []
+            }
+        }
     }
 }
 
@@ -615,6 +1975,24 @@ mod tests {
         assert_eq!(rdr.binary_byte_offset(), None);
     }
 
+    #[test]
+    fn consume_with_matches_buffer_then_consume() {
+        let bytes = "homer\nlisa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new().build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().unwrap());
+        let mut processed = vec![];
+        rdr.consume_with(|buf| {
+            let amt = buf.iter().position(|&b| b == b'\n').unwrap() + 1;
+            processed.extend_from_slice(&buf[..amt]);
+            amt
+        });
+        assert_eq!(btos(&processed), "homer\n");
+        assert_eq!(rdr.absolute_byte_offset(), 6);
+        assert_eq!(btos(rdr.buffer()), "lisa\nmaggie\n");
+    }
+
     #[test]
     fn buffer_zero_capacity() {
         let bytes = "homer\nlisa\nmaggie";
@@ -702,4 +2080,218 @@ mod tests {
 
         assert!(!rdr.fill().unwrap());
     }
+
+    #[test]
+    fn buffer_reclaim_avoids_growth() {
+        // A buffer sized to hold the longest line plus its terminator, but
+        // much smaller than the file as a whole, should never need to grow:
+        // reclaiming the already-consumed prefix before each read should
+        // always make enough room. `Error(0)` means any growth at all would
+        // fail, so a passing `fill` here proves no allocation ever occurred.
+        let bytes = "homer\nlisa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(7)
+            .buffer_alloc(BufferAllocation::Error(0))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.extend(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(bytes, btos(&got));
+    }
+
+    #[test]
+    fn buffer_ring_basics() {
+        let bytes = "homer\nlisa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4096)
+            .ring_buffer(true)
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().unwrap());
+        assert_eq!(btos(rdr.buffer()), "homer\nlisa\nmaggie\n");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.binary_byte_offset(), None);
+    }
+
+    #[test]
+    fn buffer_ring_clone_preserves_contents() {
+        let bytes = "homer\nlisa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(16)
+            .ring_buffer(true)
+            .build();
+        {
+            let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+            assert!(rdr.fill().unwrap());
+            rdr.consume(6);
+        }
+
+        let cloned = linebuf.clone();
+        assert_eq!(btos(cloned.buf.contents()), btos(linebuf.buf.contents()));
+        assert_eq!(
+            cloned.absolute_byte_offset,
+            linebuf.absolute_byte_offset,
+        );
+    }
+
+    #[test]
+    fn buffer_ring_partial_consume_wraps() {
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(16)
+            .ring_buffer(true)
+            .build();
+        let actual_cap = linebuf.buf.capacity();
+
+        // Build input long enough to force the ring to wrap around its
+        // (possibly page-rounded) physical capacity several times over,
+        // with lines short enough that a single `fill` only partially
+        // drains the buffer.
+        let line = "abcdefgh\n";
+        let lines_needed = (actual_cap * 3) / line.len() + 1;
+        let bytes = line.repeat(lines_needed);
+
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            // Only consume one line at a time instead of the whole
+            // buffer, so the next `fill` must top up the tail while the
+            // head still holds unconsumed bytes -- the exact case ring
+            // buffering exists to make cheap, and the one most likely to
+            // expose a wraparound indexing bug.
+            let buf = rdr.buffer();
+            let amt = memchr(b'\n', buf).map(|i| i + 1).unwrap_or(buf.len());
+            got.extend_from_slice(&buf[..amt]);
+            rdr.consume(amt);
+        }
+        assert_eq!(bytes, btos(&got));
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.binary_byte_offset(), None);
+    }
+
+    #[test]
+    fn pool_recycles_normally_sized_buffers() {
+        let pool = LineBufferPool::new(LineBufferBuilder::new().capacity(16));
+
+        {
+            let mut guard = pool.checkout();
+            let mut rdr =
+                LineBufferReader::new("homer\nlisa\n".as_bytes(), &mut guard);
+            assert!(rdr.fill().unwrap());
+            assert_eq!(btos(rdr.buffer()), "homer\nlisa\n");
+        }
+        assert_eq!(pool.stack.lock().unwrap().len(), 1);
+
+        // Checking out again should reuse the buffer we just returned
+        // rather than allocate a new one.
+        let _guard = pool.checkout();
+        assert_eq!(pool.stack.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn pool_drops_oversized_buffers() {
+        let pool = LineBufferPool::new(
+            LineBufferBuilder::new()
+                .capacity(1)
+                .buffer_alloc(BufferAllocation::Eager),
+        );
+
+        {
+            let mut guard = pool.checkout();
+            let bytes = "x".repeat(1024);
+            let mut rdr =
+                LineBufferReader::new(bytes.as_bytes(), &mut guard);
+            assert!(rdr.fill().unwrap());
+            let grown = guard.buf.as_ref().unwrap().buf.capacity();
+            assert!(grown > 1);
+        }
+        // The buffer grew far past the pool's configured capacity, so it
+        // should've been dropped instead of recycled.
+        assert_eq!(pool.stack.lock().unwrap().len(), 0);
+    }
+
+    fn drain<L: BufferedLayer>(mut layer: L) -> Vec<u8> {
+        let mut got = vec![];
+        while layer.fill().unwrap() {
+            got.extend_from_slice(layer.buffer());
+            layer.consume_all();
+        }
+        got
+    }
+
+    #[test]
+    fn limitor_caps_total_bytes() {
+        let mut linebuf = LineBufferBuilder::new().build();
+        let rdr = LineBufferReader::new(
+            "homer\nlisa\nmaggie\n".as_bytes(), &mut linebuf,
+        );
+        let limited = Limitor::new(rdr, 10);
+        assert_eq!(btos(&drain(limited)), "homer\nlisa");
+    }
+
+    #[test]
+    fn decompressor_exposes_decoded_bytes() {
+        // A no-op "decoder" is enough to exercise the layer: `Decompressor`
+        // only cares that `D: io::Read`, not what it decodes.
+        let decoder = "homer\nlisa\nmaggie\n".as_bytes();
+        let decompressor = Decompressor::new(decoder, 4096);
+        assert_eq!(btos(&drain(decompressor)), "homer\nlisa\nmaggie\n");
+    }
+
+    fn reverse_lines(bytes: &str, capacity: usize) -> Vec<String> {
+        use std::io::Cursor;
+
+        let mut rdr = ReverseLineBufferReader::new(
+            Cursor::new(bytes.as_bytes()), capacity, b'\n',
+        ).unwrap();
+        let mut lines = vec![];
+        loop {
+            loop {
+                let buf = rdr.buffer().to_vec();
+                if buf.is_empty() {
+                    break;
+                }
+                let search = if buf[buf.len() - 1] == b'\n' {
+                    &buf[..buf.len() - 1]
+                } else {
+                    &buf[..]
+                };
+                let start = memrchr(b'\n', search).map(|i| i + 1).unwrap_or(0);
+                lines.push(btos(&buf[start..]).to_string());
+                rdr.consume(buf.len() - start);
+            }
+            if !rdr.fill().unwrap() {
+                break;
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn reverse_buffer_basics() {
+        let bytes = "homer\nlisa\nmaggie\n";
+        let lines = reverse_lines(bytes, 4096);
+        assert_eq!(lines, vec![s("maggie\n"), s("lisa\n"), s("homer\n")]);
+    }
+
+    #[test]
+    fn reverse_buffer_small_capacity() {
+        let bytes = "homer\nlisa\nmaggie\n";
+        let lines = reverse_lines(bytes, 1);
+        assert_eq!(lines, vec![s("maggie\n"), s("lisa\n"), s("homer\n")]);
+    }
+
+    #[test]
+    fn reverse_buffer_no_trailing_newline() {
+        let bytes = "homer\nlisa\nmaggie";
+        let lines = reverse_lines(bytes, 4096);
+        assert_eq!(lines, vec![s("maggie"), s("lisa\n"), s("homer\n")]);
+    }
 }